@@ -1,17 +1,209 @@
-use std::{ops::Range, path::PathBuf, sync::Arc};
+#[cfg(feature = "uring")]
+use std::rc::Rc;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use color_eyre::eyre::{anyhow, bail, Context as _, Result};
+use color_eyre::eyre::{anyhow, bail, Context as _, Report, Result};
 
 use futures_util::{future::try_join_all, StreamExt};
-use reqwest::header::HeaderValue;
-use tokio::{fs, io::AsyncWriteExt, task};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(not(feature = "uring"))]
+use tokio::task;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
 
 static USER_AGENT: &str = concat!("zrglng/", env!("CARGO_PKG_VERSION"));
 
+/// How much progress a part downloads between manifest flushes. Keeps a
+/// crash from losing more than this much of a part's progress, without
+/// hitting the disk on every single chunk.
+const MANIFEST_FLUSH_BYTES: u64 = 1024 * 1024;
+
+/// An error from a single attempt, tagged with whether it's worth retrying.
+enum AttemptError {
+    /// Connection errors, 5xx responses, mid-stream read failures: try again.
+    Transient(Report),
+    /// Anything else (4xx, bad input, disk errors): retrying won't help.
+    Fatal(Report),
+}
+
+impl From<Report> for AttemptError {
+    fn from(report: Report) -> Self {
+        AttemptError::Fatal(report)
+    }
+}
+
+fn classify_status(status: reqwest::StatusCode, report: Report) -> AttemptError {
+    if status.is_server_error() {
+        AttemptError::Transient(report)
+    } else {
+        AttemptError::Fatal(report)
+    }
+}
+
+fn classify_reqwest_err(err: reqwest::Error, context: &str) -> AttemptError {
+    let transient = err.is_connect()
+        || err.is_timeout()
+        || err.is_body()
+        || err
+            .status()
+            .map(|status| status.is_server_error())
+            .unwrap_or(false);
+    let report = Report::new(err).wrap_err(context.to_owned());
+    if transient {
+        AttemptError::Transient(report)
+    } else {
+        AttemptError::Fatal(report)
+    }
+}
+
+/// Writes `buf` at `offset` in `file` without touching the file's shared
+/// cursor, so concurrent parts can write into the same file with no mutex.
+#[cfg(all(unix, not(feature = "uring")))]
+fn write_at(file: &std::fs::File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(all(windows, not(feature = "uring")))]
+fn write_at(file: &std::fs::File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    let mut written = 0;
+    while written < buf.len() {
+        let n = file.seek_write(&buf[written..], offset + written as u64)?;
+        written += n;
+    }
+    Ok(())
+}
+
+/// The handle each `PartialGetter` writes through. Plain builds bounce
+/// positional writes through the blocking threadpool (see `write_at`);
+/// the `uring` build drives them straight off an io_uring submission queue
+/// instead, which is why it needs a different (non-thread-shared) handle.
+#[cfg(not(feature = "uring"))]
+type FileHandle = Arc<std::fs::File>;
+#[cfg(feature = "uring")]
+type FileHandle = Rc<tokio_uring::fs::File>;
+
+#[cfg(not(feature = "uring"))]
+fn open_file_handle(file: std::fs::File) -> FileHandle {
+    Arc::new(file)
+}
+#[cfg(feature = "uring")]
+fn open_file_handle(file: std::fs::File) -> FileHandle {
+    Rc::new(tokio_uring::fs::File::from_std(file))
+}
+
+/// Writes `buf` at `offset` into `file`. Plain builds spawn the blocking
+/// positional write onto tokio's blocking pool; the `uring` build submits it
+/// directly to the io_uring queue and awaits its completion in place.
+#[cfg(not(feature = "uring"))]
+async fn write_chunk(file: FileHandle, buf: Vec<u8>, offset: u64) -> std::io::Result<()> {
+    task::spawn_blocking(move || write_at(&file, &buf, offset))
+        .await
+        .expect("positional write task panicked")
+}
+#[cfg(feature = "uring")]
+async fn write_chunk(file: FileHandle, buf: Vec<u8>, offset: u64) -> std::io::Result<()> {
+    let (result, _buf) = file.write_all_at(buf, offset).await;
+    result
+}
+
+/// Spawns a part's download+write future. Plain builds hand it to tokio's
+/// normal multi-threaded scheduler; the `uring` build's `FileHandle` isn't
+/// `Send`, so it runs on the current-thread runtime `tokio_uring::start` sets
+/// up instead.
+#[cfg(not(feature = "uring"))]
+fn spawn_get<F>(fut: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(fut)
+}
+#[cfg(feature = "uring")]
+fn spawn_get<F>(fut: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: std::future::Future + 'static,
+{
+    tokio_uring::spawn(fut)
+}
+
+/// Exponential backoff with jitter, applied between retry attempts.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            base_delay: Duration::from_secs_f64(1.0),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    /// Delay before the attempt after `attempt_no` (0-indexed), `base * 2^n`
+    /// capped at `max_delay`, with +-50% jitter to avoid a thundering herd.
+    fn backoff(&self, attempt_no: u32) -> Duration {
+        let exp = self.base_delay.as_secs_f64() * 2f64.powi(attempt_no as i32);
+        let capped = exp.min(self.max_delay.as_secs_f64());
+        let jitter = rand::thread_rng().gen_range(-0.5..=0.5);
+        Duration::from_secs_f64((capped + capped * jitter).max(0.0))
+    }
+}
+
+/// Runs `attempt` up to `policy.retries` extra times, backing off between
+/// tries, and only surfaces an error once the final attempt has failed.
+async fn with_retry<T, F, Fut>(policy: &RetryPolicy, what: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = std::result::Result<T, AttemptError>>,
+{
+    let mut attempt_no = 0;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(AttemptError::Fatal(err)) => return Err(err),
+            Err(AttemptError::Transient(err)) => {
+                if attempt_no >= policy.retries {
+                    return Err(err).with_context(|| {
+                        format!("{} failed after {} attempts", what, attempt_no + 1)
+                    });
+                }
+                let delay = policy.backoff(attempt_no);
+                log::warn!(
+                    "{} failed ({:#}), retrying in {:?} (attempt {}/{})",
+                    what,
+                    err,
+                    delay,
+                    attempt_no + 1,
+                    policy.retries
+                );
+                tokio::time::sleep(delay).await;
+                attempt_no += 1;
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct FileInfo {
     content_length: u64,
     supports_range: bool,
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,45 +212,292 @@ struct RangeQuery {
     idx: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 struct Context {
     client: Arc<reqwest::Client>,
+    retries: RetryPolicy,
+    manifest: Arc<ManifestState>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 struct PartialGetter {
     range: Range<u64>,
     idx: u64,
     url: String,
-    dest: PathBuf,
+    file: FileHandle,
+    progress: Option<PartProgress>,
+}
+
+/// The bars a single part reports into: its own, and the aggregate for the
+/// whole file. Cloning a `ProgressBar` is cheap, it's a handle to shared state.
+#[derive(Debug, Clone)]
+struct PartProgress {
+    part: ProgressBar,
+    overall: ProgressBar,
+}
+
+impl PartProgress {
+    fn inc(&self, delta: u64) {
+        self.part.inc(delta);
+        self.overall.inc(delta);
+    }
+}
+
+/// Owns the `MultiProgress` rendered on stderr and the aggregate bar for the
+/// whole file; hands out a `PartProgress` for each spawned `PartialGetter`.
+struct ProgressReporter {
+    multi: MultiProgress,
+    overall: ProgressBar,
+}
+
+impl ProgressReporter {
+    fn new(total_len: u64) -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(total_len));
+        overall.set_style(progress_style());
+        overall.set_message("total");
+        Self { multi, overall }
+    }
+
+    fn add_part(&self, idx: u64, len: u64) -> PartProgress {
+        let part = self.multi.add(ProgressBar::new(len));
+        part.set_style(progress_style());
+        part.set_message(format!("part {}", idx));
+        PartProgress {
+            part,
+            overall: self.overall.clone(),
+        }
+    }
+
+    fn finish(&self, message: &'static str) {
+        self.overall.finish_with_message(message);
+    }
+}
+
+fn progress_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg:>8} {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+    )
+    .expect("invalid progress bar template - this is a bug")
+}
+
+/// Path of the sidecar manifest recording the ranges a download was split into,
+/// e.g. `some/dir/.file.txt.zrglng` for `dest == some/dir/file.txt`.
+fn manifest_path(dest: &Path) -> PathBuf {
+    let mut path = PathBuf::from(dest);
+    let mut filename = std::ffi::OsString::from(".");
+    let final_filename = dest
+        .file_name()
+        .expect("was supposed to be a filename")
+        .to_owned();
+    filename.push(final_filename);
+    filename.push(".zrglng");
+    path.set_file_name(&filename);
+    path
+}
+
+/// Sidecar file persisted next to a download so a killed transfer can be
+/// resumed safely: if the remote file changed since, the manifest no longer
+/// matches and the whole download starts over. Each range also tracks how
+/// many of its bytes have actually landed in the destination file (flushed
+/// periodically, see [`ManifestState::record_progress`]), since parts are
+/// written in place and there's no leftover temp file to stat for partial
+/// progress across process restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+    total_length: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    ranges: Vec<Range<u64>>,
+    written: Vec<u64>,
+}
+
+impl Manifest {
+    fn fresh(file_info: &FileInfo, ranges: &[RangeQuery]) -> Self {
+        Self {
+            total_length: file_info.content_length,
+            etag: file_info.etag.clone(),
+            last_modified: file_info.last_modified.clone(),
+            ranges: ranges.iter().map(|r| r.range.clone()).collect(),
+            written: vec![0; ranges.len()],
+        }
+    }
+
+    fn matches(&self, info: &FileInfo) -> bool {
+        self.total_length == info.content_length
+            && self.etag == info.etag
+            && self.last_modified == info.last_modified
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = format!(
+            "total_length={}\netag={}\nlast_modified={}\n",
+            self.total_length,
+            self.etag.as_deref().unwrap_or(""),
+            self.last_modified.as_deref().unwrap_or(""),
+        );
+        for (range, &written) in self.ranges.iter().zip(&self.written) {
+            out.push_str(&format!("part={}-{}:{}\n", range.start, range.end, written));
+        }
+        out
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut total_length = None;
+        let mut etag = None;
+        let mut last_modified = None;
+        let mut ranges = Vec::new();
+        let mut written = Vec::new();
+        for line in contents.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "total_length" => total_length = Some(value.parse().ok()?),
+                "etag" => etag = (!value.is_empty()).then(|| value.to_owned()),
+                "last_modified" => last_modified = (!value.is_empty()).then(|| value.to_owned()),
+                "part" => {
+                    let (range, part_written) = value.split_once(':')?;
+                    let (start, end) = range.split_once('-')?;
+                    let start: u64 = start.parse().ok()?;
+                    let end: u64 = end.parse().ok()?;
+                    let part_written: u64 = part_written.parse().ok()?;
+                    if end < start || part_written > end - start {
+                        return None;
+                    }
+                    ranges.push(start..end);
+                    written.push(part_written);
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            total_length: total_length?,
+            etag,
+            last_modified,
+            ranges,
+            written,
+        })
+    }
+}
+
+/// Guards the manifest on disk with a mutex held across the whole
+/// read-modify-write-to-disk sequence, so concurrent parts recording their
+/// progress can't race each other's writes and clobber one another on disk.
+struct ManifestState {
+    path: PathBuf,
+    manifest: Mutex<Manifest>,
+}
+
+impl ManifestState {
+    fn new(path: PathBuf, manifest: Manifest) -> Self {
+        Self {
+            path,
+            manifest: Mutex::new(manifest),
+        }
+    }
+
+    /// Bytes already recorded as written for `idx`, to resume `attempt()`'s
+    /// request from instead of the start of the range.
+    async fn written_bytes(&self, idx: u64) -> u64 {
+        self.manifest
+            .lock()
+            .await
+            .written
+            .get(idx as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Records that `idx` now has `written_bytes` landed on disk, and
+    /// persists the whole manifest before releasing the lock, so a
+    /// concurrent update to another part's progress can't write a stale
+    /// snapshot over this one.
+    async fn record_progress(&self, idx: u64, written_bytes: u64) -> Result<()> {
+        let mut manifest = self.manifest.lock().await;
+        if let Some(written) = manifest.written.get_mut(idx as usize) {
+            *written = written_bytes;
+        }
+        let contents = manifest.serialize();
+        fs::write(&self.path, contents)
+            .await
+            .with_context(|| format!("failed to update manifest at {}", self.path.display()))
+    }
+
+    async fn remove(&self) {
+        fs::remove_file(&self.path).await.ok();
+    }
 }
 
 impl PartialGetter {
-    fn new(range: RangeQuery, url: &str, dest: &PathBuf) -> Self {
+    fn new(
+        range: RangeQuery,
+        url: &str,
+        file: FileHandle,
+        reporter: Option<&ProgressReporter>,
+    ) -> Self {
         let RangeQuery { range, idx } = range;
-        let dest = {
-            let mut dest = PathBuf::from(dest);
-            let mut filename = std::ffi::OsString::from(".");
-            let final_filename = dest
-                .file_name()
-                .expect("was supposed to be a filename")
-                .to_owned();
-            filename.push(final_filename);
-            filename.push(format!(".part-{}", idx));
-            log::info!("downloading part {} at {:?}", idx, &filename);
-            dest.set_file_name(&filename);
-            dest
-        };
+        log::info!("downloading part {} for range {:?}", idx, &range);
+        let progress = reporter.map(|reporter| reporter.add_part(idx, range.end - range.start));
         Self {
             range,
             idx,
             url: url.to_owned(),
-            dest,
+            file,
+            progress,
+        }
+    }
+
+    async fn get(self, ctx: Context) -> Result<u64> {
+        let full_len = self.range.end - self.range.start;
+        let resumed_from = ctx.manifest.written_bytes(self.idx).await;
+        if resumed_from >= full_len {
+            log::info!("part {} already completed, skipping", self.idx);
+            if let Some(progress) = &self.progress {
+                progress.part.set_position(full_len);
+                progress.overall.inc(full_len);
+                progress.part.finish_with_message("done");
+            }
+            return Ok(self.idx);
+        }
+        if resumed_from > 0 {
+            log::info!(
+                "part {} resuming from byte {} recorded in the manifest",
+                self.idx,
+                resumed_from
+            );
+            if let Some(progress) = &self.progress {
+                progress.part.set_position(resumed_from);
+                progress.overall.inc(resumed_from);
+            }
+        }
+
+        // Tracks bytes written so far, seeded from the manifest so a
+        // restart after a crash resumes from the last flush (see
+        // `attempt`'s periodic `record_progress` calls) instead of zero.
+        let written = AtomicU64::new(resumed_from);
+        let what = format!("part {}", self.idx);
+        with_retry(&ctx.retries, &what, || self.attempt(&ctx, &written)).await?;
+
+        ctx.manifest.record_progress(self.idx, full_len).await?;
+        if let Some(progress) = &self.progress {
+            progress.part.finish_with_message("done");
         }
+        Ok(self.idx)
     }
 
-    async fn get(self, ctx: Context) -> Result<(u64, PathBuf)> {
-        let range_header = format!("bytes={}-{}", self.range.start, self.range.end - 1);
+    async fn attempt(
+        &self,
+        ctx: &Context,
+        written: &AtomicU64,
+    ) -> std::result::Result<(), AttemptError> {
+        let full_len = self.range.end - self.range.start;
+        let existing = written.load(Ordering::Relaxed);
+
+        if existing >= full_len {
+            return Ok(());
+        }
+
+        let resume_start = self.range.start + existing;
+        let range_header = format!("bytes={}-{}", resume_start, self.range.end - 1);
 
         let res = ctx
             .client
@@ -66,49 +505,82 @@ impl PartialGetter {
             .header(reqwest::header::RANGE, range_header.as_str())
             .send()
             .await
-            .with_context(|| {
-                format!(
-                    "failed partial get #{} for range: {:?}",
-                    self.idx, self.range
+            .map_err(|err| {
+                classify_reqwest_err(
+                    err,
+                    &format!(
+                        "failed partial get #{} for range: {:?}",
+                        self.idx, self.range
+                    ),
                 )
             })?;
 
         if !res.status().is_success() {
-            bail!("invalid status code: {}", res.status());
+            return Err(classify_status(
+                res.status(),
+                anyhow!("invalid status code: {}", res.status()),
+            ));
         }
 
-        log::debug!("creating file");
-        let mut file = fs::File::create(PathBuf::from(&self.dest))
-            .await
-            .with_context(|| format!("failed to create tmp file at {}", &self.dest.display()))?;
-        log::debug!("copying chunks from res to file");
+        if existing > 0 {
+            log::info!(
+                "resuming part {} from byte {} ({} bytes of the part already written)",
+                self.idx,
+                resume_start,
+                existing
+            );
+        }
+        log::debug!("copying chunks from res to file at offset {}", resume_start);
 
         let mut bytes_stream = res.bytes_stream();
-        let mut count = 0;
+        let mut count = existing;
+        let mut last_flushed = existing;
 
         while let Some(bytes) = bytes_stream.next().await {
-            let bytes =
-                bytes.with_context(|| format!("failed to read from body at byte {}", count))?;
-            count += bytes.len();
-            // log::info!(
-            //     "part {}: {}/{} bytes",
-            //     self.idx,
-            //     count,
-            //     self.range.end - self.range.start
-            // );
-
-            file.write_all(&bytes)
+            let bytes = bytes.map_err(|err| {
+                classify_reqwest_err(err, &format!("failed to read from body at byte {}", count))
+            })?;
+
+            let offset = self.range.start + count;
+            let file = FileHandle::clone(&self.file);
+            let buf = bytes.to_vec();
+            write_chunk(file, buf, offset)
                 .await
-                .with_context(|| format!("failed to write to file at byte {}", count))?;
+                .with_context(|| format!("failed to write to file at offset {}", offset))?;
+
+            count += bytes.len() as u64;
+            written.store(count, Ordering::Relaxed);
+            if let Some(progress) = &self.progress {
+                progress.inc(bytes.len() as u64);
+            }
+
+            // Flush progress to the manifest every MANIFEST_FLUSH_BYTES so a
+            // crash mid-part loses at most that much, rather than the part's
+            // entire progress (see `get`'s resume-from-manifest handling).
+            if count - last_flushed >= MANIFEST_FLUSH_BYTES {
+                ctx.manifest.record_progress(self.idx, count).await?;
+                last_flushed = count;
+            }
         }
 
         log::debug!("finished downloading part {}", self.idx);
-        Ok((self.idx, self.dest))
+        Ok(())
     }
 }
 
-async fn file_info(client: &reqwest::Client, url: &str) -> Result<FileInfo> {
-    let res = client.head(url).send().await?;
+async fn file_info(client: &reqwest::Client, url: &str, retries: &RetryPolicy) -> Result<FileInfo> {
+    with_retry(retries, "HEAD request", || file_info_attempt(client, url)).await
+}
+
+async fn file_info_attempt(
+    client: &reqwest::Client,
+    url: &str,
+) -> std::result::Result<FileInfo, AttemptError> {
+    let res = client
+        .head(url)
+        .send()
+        .await
+        .map_err(|err| classify_reqwest_err(err, "failed to send HEAD request"))?;
 
     log::info!(
         "file_info: {:#?}, content_length: {:?}",
@@ -116,7 +588,10 @@ async fn file_info(client: &reqwest::Client, url: &str) -> Result<FileInfo> {
         res.headers().get(reqwest::header::CONTENT_LENGTH)
     );
     if !res.status().is_success() {
-        bail!("invalid status code");
+        return Err(classify_status(
+            res.status(),
+            anyhow!("invalid status code: {}", res.status()),
+        ));
     }
     let content_length = res
         .headers()
@@ -132,20 +607,90 @@ async fn file_info(client: &reqwest::Client, url: &str) -> Result<FileInfo> {
         .get(reqwest::header::ACCEPT_RANGES)
         .map(|v| v == "bytes")
         .unwrap_or(false);
+    let etag = res
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(ToOwned::to_owned);
+    let last_modified = res
+        .headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .map(ToOwned::to_owned);
     Ok(FileInfo {
         content_length,
         supports_range,
+        etag,
+        last_modified,
     })
 }
 
-async fn parallel_get(url: &str, dest: PathBuf, parts: u64) -> Result<()> {
+/// Recovers the real size of a file whose HEAD response omitted or zeroed
+/// `Content-Length` (common for dynamic endpoints and some CDNs) by issuing a
+/// single-byte range request and reading the total off `Content-Range:
+/// bytes 0-0/TOTAL`. Returns `None` if the server doesn't answer with a
+/// usable `Content-Range`, e.g. because it doesn't really support ranges.
+async fn probe_content_length(
+    client: &reqwest::Client,
+    url: &str,
+    retries: &RetryPolicy,
+) -> Result<Option<u64>> {
+    with_retry(retries, "range probe", || {
+        probe_content_length_attempt(client, url)
+    })
+    .await
+}
+
+async fn probe_content_length_attempt(
+    client: &reqwest::Client,
+    url: &str,
+) -> std::result::Result<Option<u64>, AttemptError> {
+    let res = client
+        .get(url)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(|err| classify_reqwest_err(err, "failed to send range probe request"))?;
+
+    if !res.status().is_success() {
+        return Err(classify_status(
+            res.status(),
+            anyhow!("invalid status code: {}", res.status()),
+        ));
+    }
+
+    let total = res
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse::<u64>().ok());
+    Ok(total)
+}
+
+async fn parallel_get(
+    url: &str,
+    dest: PathBuf,
+    parts: u64,
+    retries: RetryPolicy,
+    show_progress: bool,
+    headers: HeaderMap,
+    proxy: Option<String>,
+) -> Result<()> {
     // TODO: assert that dest is a file
-    let client = reqwest::Client::builder()
+    let mut client_builder = reqwest::Client::builder()
         .user_agent(USER_AGENT)
         .use_native_tls()
-        .build()?;
+        .default_headers(headers);
+    if let Some(proxy) = proxy {
+        client_builder = client_builder.proxy(
+            reqwest::Proxy::all(&proxy).with_context(|| format!("invalid proxy url: {}", proxy))?,
+        );
+    }
+    // else: reqwest already honors HTTP_PROXY/HTTPS_PROXY/NO_PROXY by default.
+    let client = client_builder.build()?;
 
-    let file_info = file_info(&client, url)
+    let mut file_info = file_info(&client, url, &retries)
         .await
         .context("failed to do HEAD request")?;
     log::info!(
@@ -154,38 +699,122 @@ async fn parallel_get(url: &str, dest: PathBuf, parts: u64) -> Result<()> {
         &file_info.supports_range
     );
     if !file_info.supports_range {
-        return full_get(&client, url, dest).await;
+        return full_get(&client, url, dest, show_progress).await;
     }
     if file_info.content_length == 0 {
-        bail!("file size is 0, we cannot split it");
+        log::info!("content-length missing or zero, probing the real size via a range request");
+        match probe_content_length(&client, url, &retries)
+            .await
+            .context("failed to probe content length")?
+        {
+            Some(total) if total > 0 => file_info.content_length = total,
+            _ => {
+                log::info!("range probe didn't yield a usable size, falling back to a single streaming download");
+                return full_get(&client, url, dest, show_progress).await;
+            }
+        }
+    }
+
+    let manifest_path = manifest_path(&dest);
+    let existing_manifest = match fs::read_to_string(&manifest_path).await {
+        Ok(contents) => Manifest::parse(&contents).filter(|m| m.matches(&file_info)),
+        Err(_) => None,
+    };
+    // A manifest only describes bytes actually landed in `dest`: if `dest`
+    // was deleted or truncated since, e.g. by the user, trusting its
+    // "completed" ranges would skip redownloading them and leave the output
+    // silently padded with the zeroes `set_len` preallocated below.
+    let existing_manifest = match existing_manifest {
+        Some(manifest) => match fs::metadata(&dest).await {
+            Ok(meta) if meta.len() == manifest.total_length => Some(manifest),
+            _ => {
+                log::warn!(
+                    "{} is missing or doesn't match the manifest's recorded size, starting fresh",
+                    dest.display()
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let (manifest, is_resume) = match existing_manifest {
+        Some(manifest) => {
+            log::info!("found a matching manifest, resuming previous download");
+            (manifest, true)
+        }
+        None => {
+            log::info!("no usable manifest, starting a fresh download");
+            let ranges: Vec<_> = get_ranges(file_info.content_length, parts).collect();
+            (Manifest::fresh(&file_info, &ranges), false)
+        }
+    };
+    let ranges: Vec<RangeQuery> = manifest
+        .ranges
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(idx, range)| RangeQuery {
+            range,
+            idx: idx as u64,
+        })
+        .collect();
+    log::trace!("ranges: {:?}", ranges);
+
+    // All parts write directly into this single file at their own absolute
+    // offset, so it must exist at its final size up front.
+    let out_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(!is_resume)
+        .open(&dest)
+        .await
+        .with_context(|| format!("failed to open output file at {}", dest.display()))?;
+    out_file.set_len(file_info.content_length).await?;
+    let out_file = open_file_handle(out_file.into_std().await);
+
+    if !is_resume {
+        fs::write(&manifest_path, manifest.serialize())
+            .await
+            .with_context(|| format!("failed to write manifest at {}", manifest_path.display()))?;
     }
-    let ranges = get_ranges(file_info.content_length, parts);
-    log::trace!("ranges: {:?}", ranges.clone().collect::<Vec<_>>());
+
+    let reporter = show_progress.then(|| ProgressReporter::new(file_info.content_length));
+    let manifest = Arc::new(ManifestState::new(manifest_path, manifest));
 
     let client = Arc::new(client);
-    let ctx = Context { client };
+    let ctx = Context {
+        client,
+        retries,
+        manifest,
+    };
 
-    let partial_reqs = ranges.map(|range| {
-        let partial_getter = PartialGetter::new(range, url, &dest);
+    let partial_reqs = ranges.into_iter().map(|range| {
+        let partial_getter =
+            PartialGetter::new(range, url, FileHandle::clone(&out_file), reporter.as_ref());
         let ctx = ctx.clone();
-        task::spawn(async move { partial_getter.get(ctx).await })
+        spawn_get(async move { partial_getter.get(ctx).await })
     });
 
-    let files = try_join_all(partial_reqs)
+    try_join_all(partial_reqs)
         .await
-        .context("one of the parts failed to download")?;
-    let mut out_file = fs::File::create(&dest).await?;
-    let mut files = files.into_iter().collect::<Result<Vec<_>>>()?;
-    files.sort_unstable_by_key(|&(idx, _)| idx);
-    for (_, path) in files {
-        let mut file = fs::File::open(&path).await?;
-        tokio::io::copy(&mut file, &mut out_file).await?;
-        fs::remove_file(&path).await?;
+        .context("one of the parts failed to download")?
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+    ctx.manifest.remove().await;
+    if let Some(reporter) = &reporter {
+        reporter.finish("download complete");
     }
     Ok(())
 }
 
-async fn full_get(client: &reqwest::Client, url: &str, dest: PathBuf) -> Result<()> {
+async fn full_get(
+    client: &reqwest::Client,
+    url: &str,
+    dest: PathBuf,
+    show_progress: bool,
+) -> Result<()> {
     let res = client
         .get(url)
         .send()
@@ -198,6 +827,12 @@ async fn full_get(client: &reqwest::Client, url: &str, dest: PathBuf) -> Result<
     let content_length = res
         .content_length()
         .ok_or_else(|| anyhow!("no content length"))?;
+    let bar = show_progress.then(|| {
+        let bar = ProgressBar::new(content_length);
+        bar.set_style(progress_style());
+        bar.set_message("total");
+        bar
+    });
 
     log::debug!("creating file");
     let mut file = fs::File::create(PathBuf::from(&dest))
@@ -212,12 +847,18 @@ async fn full_get(client: &reqwest::Client, url: &str, dest: PathBuf) -> Result<
         let bytes = bytes.with_context(|| format!("failed to read from body at byte {}", count))?;
         count += bytes.len();
         log::info!("{}/{} bytes", count, content_length);
+        if let Some(bar) = &bar {
+            bar.inc(bytes.len() as u64);
+        }
 
         file.write_all(&bytes)
             .await
             .with_context(|| format!("failed to write to file at byte {}", count))?;
     }
 
+    if let Some(bar) = &bar {
+        bar.finish_with_message("download complete");
+    }
     log::debug!("finished downloading");
     Ok(())
 }
@@ -239,6 +880,23 @@ fn get_ranges(content_length: u64, parts: u64) -> impl Iterator<Item = RangeQuer
     })
 }
 
+/// Parses `--header` values of the form `"Name: Value"` into a `HeaderMap`
+/// suitable for `ClientBuilder::default_headers`.
+fn parse_headers(raw: &[String]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for entry in raw {
+        let (name, value) = entry
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid header {:?}, expected \"Name: Value\"", entry))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .with_context(|| format!("invalid header name in {:?}", entry))?;
+        let value = HeaderValue::from_str(value.trim())
+            .with_context(|| format!("invalid header value in {:?}", entry))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
 fn dest_from_url(url: &url::Url) -> PathBuf {
     if let Some(segments) = url.path_segments() {
         let last_segment = segments.last().unwrap_or("index.html");
@@ -256,7 +914,19 @@ async fn run(args: Args) -> Result<()> {
     let begin = tokio::time::Instant::now();
     let url = url::Url::parse(args.url.as_str())?;
     let dest = args.output.unwrap_or_else(|| dest_from_url(&url));
-    parallel_get(args.url.as_str(), dest, args.parts).await?;
+    let retries = RetryPolicy::new(args.retries);
+    let show_progress = args.progress && !args.quiet;
+    let headers = parse_headers(&args.headers)?;
+    parallel_get(
+        args.url.as_str(),
+        dest,
+        args.parts,
+        retries,
+        show_progress,
+        headers,
+        args.proxy,
+    )
+    .await?;
     println!("finished in {} milliseconds.", begin.elapsed().as_millis());
     Ok(())
 }
@@ -267,25 +937,115 @@ struct Args {
     parts: u64,
     #[structopt(long, short, parse(from_os_str))]
     output: Option<PathBuf>,
+    /// Number of times to retry a failed part before giving up.
+    #[structopt(long, default_value = "5")]
+    retries: u32,
+    /// Show live per-part progress bars on stderr.
+    #[structopt(long)]
+    progress: bool,
+    /// Suppress progress bars even if --progress was passed.
+    #[structopt(long)]
+    quiet: bool,
+    /// Extra request header, as "Name: Value". Can be passed multiple times.
+    #[structopt(long = "header", short = "H", number_of_values = 1)]
+    headers: Vec<String>,
+    /// Proxy URL to send requests through. Defaults to honoring
+    /// HTTP_PROXY/HTTPS_PROXY/NO_PROXY if unset.
+    #[structopt(long)]
+    proxy: Option<String>,
     url: String,
 }
 
+async fn run_and_report(args: Args) {
+    if let Err(err) = run(args).await {
+        eprintln!("Error: {}", err);
+        let sources = std::iter::successors(err.source(), |err| err.source());
+        for source in sources {
+            eprintln!("  caused by: {}", source);
+        }
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     color_eyre::install().expect("failexd to install color eyre handler - this is a bug");
     pretty_env_logger::init_timed();
     let args = <Args as structopt::StructOpt>::from_args();
+
+    // The `uring` build drives writes straight off io_uring, which only
+    // hands out completions on the thread that submitted them, so it runs on
+    // tokio_uring's single-threaded runtime instead of tokio's default one.
+    #[cfg(not(feature = "uring"))]
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .expect("could not create tokio runtime")
-        .block_on(async {
-            if let Err(err) = run(args).await {
-                eprintln!("Error: {}", err);
-                let sources = std::iter::successors(err.source(), |err| err.source());
-                for source in sources {
-                    eprintln!("  caused by: {}", source);
-                }
-                std::process::exit(1);
-            }
-        })
+        .block_on(run_and_report(args));
+    #[cfg(feature = "uring")]
+    tokio_uring::start(run_and_report(args));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_manifest() -> Manifest {
+        Manifest {
+            total_length: 300,
+            etag: Some("\"abc123\"".to_owned()),
+            last_modified: Some("Tue, 15 Nov 1994 12:45:26 GMT".to_owned()),
+            ranges: vec![0..100, 100..200, 200..300],
+            written: vec![100, 42, 0],
+        }
+    }
+
+    #[test]
+    fn serialize_then_parse_roundtrips() {
+        let manifest = sample_manifest();
+        let parsed = Manifest::parse(&manifest.serialize()).expect("should parse");
+        assert_eq!(manifest, parsed);
+    }
+
+    #[test]
+    fn parse_treats_absent_etag_and_last_modified_as_none() {
+        let mut manifest = sample_manifest();
+        manifest.etag = None;
+        manifest.last_modified = None;
+        let parsed = Manifest::parse(&manifest.serialize()).expect("should parse");
+        assert_eq!(manifest, parsed);
+    }
+
+    #[test]
+    fn parse_rejects_missing_total_length() {
+        let contents = "etag=\nlast_modified=\npart=0-100:0\n";
+        assert!(Manifest::parse(contents).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_a_part_with_more_bytes_written_than_its_length() {
+        let contents = "total_length=100\netag=\nlast_modified=\npart=0-100:101\n";
+        assert!(Manifest::parse(contents).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_a_part_whose_range_end_is_before_its_start() {
+        let contents = "total_length=100\netag=\nlast_modified=\npart=100-50:0\n";
+        assert!(Manifest::parse(contents).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_truncated_lines() {
+        let contents = "total_length=100\npart=0-100\n";
+        assert!(Manifest::parse(contents).is_none());
+    }
+
+    #[test]
+    fn parse_ignores_unknown_keys() {
+        let contents =
+            "total_length=100\netag=\nlast_modified=\nsome_future_field=1\npart=0-100:0\n";
+        let parsed = Manifest::parse(contents).expect("should parse");
+        assert_eq!(parsed.total_length, 100);
+        assert_eq!(parsed.ranges, vec![0..100]);
+        assert_eq!(parsed.written, vec![0]);
+    }
 }